@@ -0,0 +1,647 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Computation of the Authenticode message digest.
+//!
+//! The Authenticode digest covers almost all of a PE image, but skips
+//! over the handful of fields that are rewritten when the image is
+//! signed: the header checksum, the certificate-table entry in the
+//! data directory, and the attribute certificate table itself.
+
+extern crate alloc;
+
+use crate::usize_from_u32;
+use crate::PeTrait;
+use alloc::vec::Vec;
+use core::fmt::{self, Display, Formatter};
+use core::ops::Range;
+
+#[cfg(feature = "digest")]
+use digest::{Digest, Output};
+
+/// Index of the certificate table entry within the optional header's
+/// data directory.
+const IMAGE_DIRECTORY_ENTRY_SECURITY: usize = 4;
+
+/// Magic value identifying a 32-bit (`PE32`) optional header.
+const IMAGE_NT_OPTIONAL_HDR32_MAGIC: u16 = 0x10b;
+
+/// Magic value identifying a 64-bit (`PE32+`) optional header.
+const IMAGE_NT_OPTIONAL_HDR64_MAGIC: u16 = 0x20b;
+
+/// Error returned by [`authenticode_digest_ranges`] and
+/// [`authenticode_digest`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AuthenticodeDigestError {
+    /// The file is too small to contain a DOS header.
+    MissingDosHeader,
+
+    /// The DOS header's `e_lfanew` field points outside of the file, or
+    /// the PE signature is missing.
+    MissingPeHeader,
+
+    /// The optional header's magic value is not a recognized `PE32` or
+    /// `PE32+` value.
+    InvalidOptionalHeaderMagic(u16),
+
+    /// The optional header, data directory, or section table extends
+    /// outside of the file.
+    HeadersOutOfBounds,
+
+    /// A section's raw data extends outside of the file.
+    SectionOutOfBounds,
+
+    /// The certificate-table entry in the data directory extends
+    /// outside of the file.
+    CertificateTableOutOfBounds,
+}
+
+impl Display for AuthenticodeDigestError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingDosHeader => write!(f, "file is too small to contain a DOS header"),
+            Self::MissingPeHeader => {
+                write!(f, "DOS header does not point to a valid PE signature")
+            }
+            Self::InvalidOptionalHeaderMagic(magic) => {
+                write!(f, "invalid optional header magic: {magic:#06x}")
+            }
+            Self::HeadersOutOfBounds => {
+                write!(f, "PE headers extend outside of the file")
+            }
+            Self::SectionOutOfBounds => {
+                write!(f, "section raw data extends outside of the file")
+            }
+            Self::CertificateTableOutOfBounds => {
+                write!(f, "certificate table extends outside of the file")
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for AuthenticodeDigestError {}
+
+/// Layout information extracted from the PE headers that is needed to
+/// compute the Authenticode digest ranges.
+struct PeLayout {
+    /// Range of the 4-byte `CheckSum` field in the optional header.
+    checksum: Range<usize>,
+
+    /// Range of the 8-byte certificate-table entry in the data
+    /// directory.
+    cert_dir_entry: Range<usize>,
+
+    /// Value of the optional header's `SizeOfHeaders` field: the end of
+    /// all the headers (DOS header, PE header, optional header, and
+    /// section table).
+    size_of_headers: usize,
+
+    /// Offset of the first section header.
+    section_table_offset: usize,
+
+    /// Number of sections.
+    num_sections: usize,
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset.checked_add(2)?)?;
+    Some(u16::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn parse_pe_layout(data: &[u8]) -> Result<PeLayout, AuthenticodeDigestError> {
+    // The `e_lfanew` field of the DOS header is a 4-byte offset to the
+    // PE signature, located at offset 0x3c.
+    let e_lfanew = read_u32(data, 0x3c).ok_or(AuthenticodeDigestError::MissingDosHeader)?;
+    let pe_sig_offset = usize_from_u32(e_lfanew);
+
+    let pe_sig = data
+        .get(
+            pe_sig_offset
+                ..pe_sig_offset
+                    .checked_add(4)
+                    .ok_or(AuthenticodeDigestError::MissingPeHeader)?,
+        )
+        .ok_or(AuthenticodeDigestError::MissingPeHeader)?;
+    if pe_sig != b"PE\0\0" {
+        return Err(AuthenticodeDigestError::MissingPeHeader);
+    }
+
+    // The COFF file header immediately follows the 4-byte PE signature.
+    let coff_header_offset = pe_sig_offset + 4;
+    let num_sections = read_u16(data, coff_header_offset + 2)
+        .ok_or(AuthenticodeDigestError::HeadersOutOfBounds)?;
+    let size_of_optional_header = read_u16(data, coff_header_offset + 16)
+        .ok_or(AuthenticodeDigestError::HeadersOutOfBounds)?;
+
+    // The optional header immediately follows the 20-byte COFF header.
+    let optional_header_offset = coff_header_offset + 20;
+    let magic = read_u16(data, optional_header_offset)
+        .ok_or(AuthenticodeDigestError::HeadersOutOfBounds)?;
+    if magic != IMAGE_NT_OPTIONAL_HDR32_MAGIC && magic != IMAGE_NT_OPTIONAL_HDR64_MAGIC {
+        return Err(AuthenticodeDigestError::InvalidOptionalHeaderMagic(magic));
+    }
+
+    // `SizeOfHeaders` and `CheckSum` sit at the same offset in both the
+    // `PE32` and `PE32+` optional headers: the extra 4 bytes that
+    // `PE32+` spends on a wider `ImageBase` are exactly offset by the
+    // `BaseOfData` field that only `PE32` has.
+    let size_of_headers_offset = optional_header_offset + 60;
+    let size_of_headers = read_u32(data, size_of_headers_offset)
+        .ok_or(AuthenticodeDigestError::HeadersOutOfBounds)?;
+    let checksum_start = optional_header_offset + 64;
+
+    // The data directory follows the rest of the optional header, whose
+    // size differs between `PE32` and `PE32+`.
+    let data_directory_offset = if magic == IMAGE_NT_OPTIONAL_HDR32_MAGIC {
+        optional_header_offset + 96
+    } else {
+        optional_header_offset + 112
+    };
+    let cert_dir_entry_start = data_directory_offset + IMAGE_DIRECTORY_ENTRY_SECURITY * 8;
+
+    let section_table_offset =
+        optional_header_offset + usize_from_u32(u32::from(size_of_optional_header));
+
+    let layout = PeLayout {
+        checksum: checksum_start..(checksum_start + 4),
+        cert_dir_entry: cert_dir_entry_start..(cert_dir_entry_start + 8),
+        size_of_headers: usize_from_u32(size_of_headers),
+        section_table_offset,
+        num_sections: usize::from(num_sections),
+    };
+
+    // Sanity-check that everything we just computed actually lies
+    // within the file, and that `SizeOfHeaders` is at least big enough
+    // to cover the certificate-directory entry and the section table:
+    // `SizeOfHeaders` is attacker-controlled and a value smaller than
+    // either would make `Head3`'s `cert_dir_entry.end..size_of_headers`
+    // range an inverted (start > end) range.
+    let section_table_end = layout
+        .section_table_offset
+        .checked_add(
+            layout
+                .num_sections
+                .checked_mul(40)
+                .ok_or(AuthenticodeDigestError::HeadersOutOfBounds)?,
+        )
+        .ok_or(AuthenticodeDigestError::HeadersOutOfBounds)?;
+    if data.get(..layout.cert_dir_entry.end).is_none()
+        || data.get(..layout.size_of_headers).is_none()
+        || data.get(..section_table_end).is_none()
+        || layout.size_of_headers < layout.cert_dir_entry.end
+        || layout.size_of_headers < section_table_end
+    {
+        return Err(AuthenticodeDigestError::HeadersOutOfBounds);
+    }
+
+    Ok(layout)
+}
+
+/// One section's raw-data location, as read from its section header.
+#[derive(Clone, Copy)]
+struct SectionRawData {
+    pointer_to_raw_data: usize,
+    size_of_raw_data: usize,
+}
+
+impl SectionRawData {
+    /// The offset one past the end of this section's raw data, or
+    /// `None` if `pointer_to_raw_data + size_of_raw_data` overflows
+    /// `usize`.
+    fn checked_end(&self) -> Option<usize> {
+        self.pointer_to_raw_data.checked_add(self.size_of_raw_data)
+    }
+}
+
+fn section_raw_data(
+    data: &[u8],
+    layout: &PeLayout,
+    index: usize,
+) -> Result<SectionRawData, AuthenticodeDigestError> {
+    let offset = layout.section_table_offset + index * 40;
+    let pointer_to_raw_data =
+        read_u32(data, offset + 20).ok_or(AuthenticodeDigestError::HeadersOutOfBounds)?;
+    let size_of_raw_data =
+        read_u32(data, offset + 16).ok_or(AuthenticodeDigestError::HeadersOutOfBounds)?;
+    Ok(SectionRawData {
+        pointer_to_raw_data: usize_from_u32(pointer_to_raw_data),
+        size_of_raw_data: usize_from_u32(size_of_raw_data),
+    })
+}
+
+/// Read every section's raw-data location and sort them by ascending
+/// `PointerToRawData`, breaking ties on index.
+///
+/// `NumberOfSections` is attacker-controlled, so this is computed once
+/// up front rather than by repeatedly rescanning the section table for
+/// the next-smallest entry, which would be quadratic in the number of
+/// sections.
+fn sections_by_raw_data_pointer(
+    data: &[u8],
+    layout: &PeLayout,
+) -> Result<Vec<(usize, SectionRawData)>, AuthenticodeDigestError> {
+    let mut sections = Vec::with_capacity(layout.num_sections);
+    for index in 0..layout.num_sections {
+        sections.push((index, section_raw_data(data, layout, index)?));
+    }
+    sections.sort_unstable_by_key(|(index, raw_data)| (raw_data.pointer_to_raw_data, *index));
+    Ok(sections)
+}
+
+/// Iterator over the byte ranges of a PE image that the Authenticode
+/// digest is computed over, in order.
+///
+/// Returned by [`authenticode_digest_ranges`].
+pub struct AuthenticodeDigestRanges<'a> {
+    data: &'a [u8],
+    layout: PeLayout,
+    sections: Vec<(usize, SectionRawData)>,
+    cert_table_start: Option<usize>,
+    state: DigestRangeState,
+}
+
+#[derive(Clone, Copy)]
+enum DigestRangeState {
+    Head1,
+    Head2,
+    Head3,
+    /// Index into `sections` of the next section to yield.
+    Sections {
+        next: usize,
+    },
+    Trailing,
+    Done,
+}
+
+impl<'a> Iterator for AuthenticodeDigestRanges<'a> {
+    type Item = Result<Range<usize>, AuthenticodeDigestError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.state {
+                DigestRangeState::Head1 => {
+                    self.state = DigestRangeState::Head2;
+                    return Some(Ok(0..self.layout.checksum.start));
+                }
+                DigestRangeState::Head2 => {
+                    self.state = DigestRangeState::Head3;
+                    return Some(Ok(
+                        self.layout.checksum.end..self.layout.cert_dir_entry.start
+                    ));
+                }
+                DigestRangeState::Head3 => {
+                    self.state = DigestRangeState::Sections { next: 0 };
+                    return Some(Ok(
+                        self.layout.cert_dir_entry.end..self.layout.size_of_headers
+                    ));
+                }
+                DigestRangeState::Sections { next } => match self.sections.get(next) {
+                    Some(&(_, raw_data)) => {
+                        self.state = DigestRangeState::Sections { next: next + 1 };
+                        let start = raw_data.pointer_to_raw_data;
+                        let end = match raw_data.checked_end() {
+                            Some(end) if self.data.get(start..end).is_some() => end,
+                            _ => {
+                                self.state = DigestRangeState::Done;
+                                return Some(Err(AuthenticodeDigestError::SectionOutOfBounds));
+                            }
+                        };
+                        return Some(Ok(start..end));
+                    }
+                    None => {
+                        self.state = DigestRangeState::Trailing;
+                    }
+                },
+                DigestRangeState::Trailing => {
+                    self.state = DigestRangeState::Done;
+                    let start = match self.trailing_start() {
+                        Ok(start) => start,
+                        Err(err) => return Some(Err(err)),
+                    };
+                    let end = self.cert_table_start.unwrap_or(self.data.len());
+                    if start >= end {
+                        continue;
+                    }
+                    return Some(Ok(start..end));
+                }
+                DigestRangeState::Done => return None,
+            }
+        }
+    }
+}
+
+impl<'a> AuthenticodeDigestRanges<'a> {
+    /// Offset immediately after the raw data of the last section (the
+    /// section whose raw data ends at the highest file offset).
+    fn trailing_start(&self) -> Result<usize, AuthenticodeDigestError> {
+        let mut end = self.layout.size_of_headers;
+        for (_, raw_data) in &self.sections {
+            let section_end = raw_data
+                .checked_end()
+                .ok_or(AuthenticodeDigestError::SectionOutOfBounds)?;
+            end = end.max(section_end);
+        }
+        Ok(end)
+    }
+}
+
+/// Get the ordered byte ranges of `pe` that the Authenticode digest
+/// covers.
+///
+/// The ranges run from the start of the file up to (but not including)
+/// the `CheckSum` field in the optional header, resume after it up to
+/// the certificate-table entry in the data directory, resume after that
+/// through the end of the headers, then cover each section in order of
+/// ascending `PointerToRawData`, and finally cover any trailing bytes
+/// after the last section but before the attribute certificate table.
+/// The attribute certificate table itself is never included.
+///
+/// If the image has no attribute certificate table, the final range
+/// runs to the end of the file.
+pub fn authenticode_digest_ranges(
+    pe: &dyn PeTrait,
+) -> Result<AuthenticodeDigestRanges<'_>, AuthenticodeDigestError> {
+    let data = pe.data();
+    let layout = parse_pe_layout(data)?;
+    let sections = sections_by_raw_data_pointer(data, &layout)?;
+    // `certificate_table_range` comes straight from the `PeTrait`
+    // implementation, which may not itself have validated that the
+    // Security data-directory entry lies within the file: check it here
+    // the same way `AttributeCertificateIterator` does, rather than
+    // trusting it as the end of the trailing range below.
+    let cert_table_start = match pe.certificate_table_range().ok().flatten() {
+        Some(range) => {
+            if data.get(range.clone()).is_none() {
+                return Err(AuthenticodeDigestError::CertificateTableOutOfBounds);
+            }
+            Some(range.start)
+        }
+        None => None,
+    };
+
+    Ok(AuthenticodeDigestRanges {
+        data,
+        layout,
+        sections,
+        cert_table_start,
+        state: DigestRangeState::Head1,
+    })
+}
+
+/// Compute the Authenticode message digest of `pe` using the digest
+/// algorithm `D`.
+#[cfg(feature = "digest")]
+pub fn authenticode_digest<D: Digest>(
+    pe: &dyn PeTrait,
+) -> Result<Output<D>, AuthenticodeDigestError> {
+    let data = pe.data();
+    let mut hasher = D::new();
+    for range in authenticode_digest_ranges(pe)? {
+        let range = range?;
+        // OK to unwrap: every range yielded by `authenticode_digest_ranges`
+        // is within the bounds of `data`.
+        hasher.update(data.get(range).unwrap());
+    }
+    Ok(hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build a minimal PE32 header with the given number of sections,
+    /// each with the given `(pointer_to_raw_data, size_of_raw_data)`, the
+    /// given certificate-directory entry `(offset, size)`, and the given
+    /// `SizeOfHeaders`.
+    fn build_header_with(
+        sections: &[(u32, u32)],
+        cert_dir: (u32, u32),
+        headers_size: u32,
+    ) -> Vec<u8> {
+        let mut data = vec![0u8; 0x40];
+        data[0x3c..0x40].copy_from_slice(&64u32.to_le_bytes());
+
+        data.extend_from_slice(b"PE\0\0");
+        // COFF header.
+        data.extend_from_slice(&0u16.to_le_bytes()); // Machine
+        data.extend_from_slice(&(sections.len() as u16).to_le_bytes()); // NumberOfSections
+        data.extend_from_slice(&[0u8; 12]); // TimeDateStamp, PointerToSymbolTable, NumberOfSymbols
+        data.extend_from_slice(&224u16.to_le_bytes()); // SizeOfOptionalHeader
+        data.extend_from_slice(&0u16.to_le_bytes()); // Characteristics
+
+        let optional_header_start = data.len();
+        data.extend_from_slice(&IMAGE_NT_OPTIONAL_HDR32_MAGIC.to_le_bytes());
+        data.resize(optional_header_start + 60, 0);
+        data.extend_from_slice(&headers_size.to_le_bytes()); // SizeOfHeaders
+        data.extend_from_slice(&0u32.to_le_bytes()); // CheckSum
+        data.resize(optional_header_start + 96, 0);
+        for i in 0..16u32 {
+            if i == IMAGE_DIRECTORY_ENTRY_SECURITY as u32 {
+                data.extend_from_slice(&cert_dir.0.to_le_bytes());
+                data.extend_from_slice(&cert_dir.1.to_le_bytes());
+            } else {
+                data.extend_from_slice(&[0u8; 8]);
+            }
+        }
+        assert_eq!(data.len(), optional_header_start + 224);
+
+        for (pointer, size) in sections {
+            data.extend_from_slice(&[0u8; 8]); // Name
+            data.extend_from_slice(&0u32.to_le_bytes()); // VirtualSize
+            data.extend_from_slice(&0u32.to_le_bytes()); // VirtualAddress
+            data.extend_from_slice(&size.to_le_bytes()); // SizeOfRawData
+            data.extend_from_slice(&pointer.to_le_bytes()); // PointerToRawData
+            data.extend_from_slice(&[0u8; 16]); // relocations, linenumbers, characteristics
+        }
+
+        // Pad out to `SizeOfHeaders`, unless the actual header content
+        // written above is already longer (as in tests that deliberately
+        // pass a too-small `SizeOfHeaders`).
+        data.resize(data.len().max(headers_size as usize), 0);
+        data
+    }
+
+    /// Build a minimal PE32 header with the given number of sections,
+    /// each with the given `(pointer_to_raw_data, size_of_raw_data)`, and
+    /// no certificate-directory entry.
+    fn build_header(sections: &[(u32, u32)]) -> Vec<u8> {
+        build_header_with(sections, (0, 0), 0x1000)
+    }
+
+    /// A [`PeTrait`] implementation backed by an in-memory image, used to
+    /// drive [`authenticode_digest_ranges`] end to end the same way a
+    /// real caller would.
+    struct FakePe {
+        data: Vec<u8>,
+        cert_table_range: Option<Range<usize>>,
+    }
+
+    impl PeTrait for FakePe {
+        fn data(&self) -> &[u8] {
+            &self.data
+        }
+
+        fn certificate_table_range(&self) -> Result<Option<Range<usize>>, AuthenticodeDigestError> {
+            Ok(self.cert_table_range.clone())
+        }
+    }
+
+    #[test]
+    fn test_parse_pe_layout() {
+        let data = build_header(&[(0x1000, 0x200), (0x200, 0x400)]);
+        let layout = parse_pe_layout(&data).unwrap();
+        assert_eq!(layout.num_sections, 2);
+        assert_eq!(layout.size_of_headers, 0x1000);
+    }
+
+    #[test]
+    fn test_sections_ordered_by_pointer() {
+        let mut data = build_header(&[(0x1000, 0x200), (0x200, 0x400)]);
+        data.resize(0x1200, 0xaa);
+        let layout = parse_pe_layout(&data).unwrap();
+        let sections = sections_by_raw_data_pointer(&data, &layout).unwrap();
+        let pointers: Vec<usize> = sections
+            .iter()
+            .map(|(_, raw)| raw.pointer_to_raw_data)
+            .collect();
+        assert_eq!(pointers, [0x200, 0x1000]);
+    }
+
+    /// A section whose `PointerToRawData` is near `u32::MAX` must be
+    /// rejected rather than overflowing (on 32-bit targets) or silently
+    /// accepting an out-of-bounds range (on 64-bit targets).
+    #[test]
+    fn test_section_raw_data_overflow_is_rejected() {
+        let mut data = build_header(&[(u32::MAX - 1, 0x400)]);
+        data.resize(0x1200, 0xaa);
+        let layout = parse_pe_layout(&data).unwrap();
+        let mut ranges = AuthenticodeDigestRanges {
+            data: &data,
+            sections: sections_by_raw_data_pointer(&data, &layout).unwrap(),
+            layout,
+            cert_table_start: None,
+            state: DigestRangeState::Sections { next: 0 },
+        };
+        assert_eq!(
+            ranges.next(),
+            Some(Err(AuthenticodeDigestError::SectionOutOfBounds))
+        );
+    }
+
+    /// `SizeOfHeaders` smaller than the certificate-directory entry must
+    /// be rejected rather than producing an inverted `Head3` range.
+    #[test]
+    fn test_headers_out_of_bounds_when_size_of_headers_too_small() {
+        // The certificate-directory entry sits at 0xd8..0xe0 (see
+        // `build_header`'s fixed layout), so a `SizeOfHeaders` of 0x10 is
+        // smaller than `cert_dir_entry.end`.
+        let data = build_header_with(&[], (0, 0), 0x10);
+        match parse_pe_layout(&data) {
+            Err(AuthenticodeDigestError::HeadersOutOfBounds) => {}
+            other => panic!("expected HeadersOutOfBounds, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    /// With no certificate table, the final range runs to the end of the
+    /// file, and the full state machine (`Head1`..`Trailing`) is driven
+    /// end to end through a real [`PeTrait`] implementation.
+    #[test]
+    fn test_digest_ranges_no_cert_table() {
+        let mut data = build_header(&[(0x1000, 0x100)]);
+        data.resize(0x1200, 0xaa);
+        let pe = FakePe {
+            data,
+            cert_table_range: None,
+        };
+
+        let ranges: Vec<Range<usize>> = authenticode_digest_ranges(&pe)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            ranges,
+            [
+                0..0x98,
+                0x9c..0xd8,
+                0xe0..0x1000,
+                0x1000..0x1100,
+                0x1100..0x1200
+            ]
+        );
+    }
+
+    /// With a certificate table present, the trailing range stops at the
+    /// start of the table instead of the end of the file, covering any
+    /// padding in between.
+    #[test]
+    fn test_digest_ranges_trailing_padding_before_cert_table() {
+        let mut data = build_header_with(&[(0x1000, 0x100)], (0x1200, 0x50), 0x1000);
+        data.resize(0x1250, 0xaa);
+        let pe = FakePe {
+            cert_table_range: Some(0x1200..0x1250),
+            data,
+        };
+
+        let ranges: Vec<Range<usize>> = authenticode_digest_ranges(&pe)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            ranges,
+            [
+                0..0x98,
+                0x9c..0xd8,
+                0xe0..0x1000,
+                0x1000..0x1100,
+                0x1100..0x1200
+            ]
+        );
+    }
+
+    /// A section with `SizeOfRawData` of zero yields an empty range
+    /// rather than being skipped or rejected.
+    #[test]
+    fn test_digest_ranges_zero_length_section() {
+        let data = build_header(&[(0x1000, 0)]);
+        let pe = FakePe {
+            data,
+            cert_table_range: None,
+        };
+
+        let ranges: Vec<Range<usize>> = authenticode_digest_ranges(&pe)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(ranges, [0..0x98, 0x9c..0xd8, 0xe0..0x1000, 0x1000..0x1000]);
+    }
+
+    /// A `PeTrait` implementation that reports a certificate-table range
+    /// past the end of the file (e.g. from an unvalidated Security
+    /// data-directory entry) must be rejected rather than trusted as the
+    /// end of the trailing range.
+    #[test]
+    fn test_digest_ranges_cert_table_out_of_bounds() {
+        let data = build_header(&[(0x1000, 0x100)]);
+        assert_eq!(data.len(), 0x1000);
+        let pe = FakePe {
+            data,
+            cert_table_range: Some(0x5000..0x5010),
+        };
+
+        assert_eq!(
+            authenticode_digest_ranges(&pe).err(),
+            Some(AuthenticodeDigestError::CertificateTableOutOfBounds)
+        );
+    }
+}
@@ -17,6 +17,81 @@ pub const WIN_CERT_REVISION_2_0: u16 = 0x0200;
 /// Certificate contains a PKCS#7 `SignedData` structure.
 pub const WIN_CERT_TYPE_PKCS_SIGNED_DATA: u16 = 0x0002;
 
+/// `WIN_CERTIFICATE` revision, i.e. the version of the structure used to
+/// frame an entry in the attribute certificate table.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AttributeCertificateRevision {
+    /// Legacy revision. Not supported by this crate's Authenticode
+    /// parsing, but may appear in the wild.
+    Revision1_0 = 0x0100,
+
+    /// Current revision, used by Authenticode signatures.
+    Revision2_0 = 0x0200,
+}
+
+impl TryFrom<u16> for AttributeCertificateRevision {
+    type Error = UnknownAttributeCertificateValue;
+
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        match value {
+            0x0100 => Ok(Self::Revision1_0),
+            0x0200 => Ok(Self::Revision2_0),
+            _ => Err(UnknownAttributeCertificateValue(value)),
+        }
+    }
+}
+
+/// `WIN_CERTIFICATE` certificate type, identifying the format of an
+/// attribute certificate's data.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AttributeCertificateType {
+    /// Certificate contains an X.509 certificate.
+    X509 = 0x0001,
+
+    /// Certificate contains a PKCS#7 `SignedData` structure, as used by
+    /// Authenticode.
+    PkcsSignedData = 0x0002,
+
+    /// Reserved.
+    Reserved1 = 0x0003,
+
+    /// Certificate contains a time-stamp-stack-signed blob.
+    TsStackSigned = 0x0004,
+}
+
+impl TryFrom<u16> for AttributeCertificateType {
+    type Error = UnknownAttributeCertificateValue;
+
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        match value {
+            0x0001 => Ok(Self::X509),
+            0x0002 => Ok(Self::PkcsSignedData),
+            0x0003 => Ok(Self::Reserved1),
+            0x0004 => Ok(Self::TsStackSigned),
+            _ => Err(UnknownAttributeCertificateValue(value)),
+        }
+    }
+}
+
+/// Error returned when converting a raw `u16` to
+/// [`AttributeCertificateRevision`] or [`AttributeCertificateType`] and
+/// the value is not one of the recognized variants.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct UnknownAttributeCertificateValue(pub u16);
+
+impl Display for UnknownAttributeCertificateValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown attribute certificate value: {:#06x}", self.0)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for UnknownAttributeCertificateValue {}
+
+/// Size in bytes of the `WIN_CERTIFICATE` header (`dwLength`, `wRevision`,
+/// and `wCertificateType`).
+const HEADER_SIZE: usize = 8;
+
 fn align_up_to_8(val: usize) -> Option<usize> {
     const ALIGN: usize = 8;
     let r = val % ALIGN;
@@ -30,7 +105,10 @@ fn align_up_to_8(val: usize) -> Option<usize> {
 }
 
 fn check_total_size_valid(remaining_data: &[u8]) -> bool {
-    let mut iter = AttributeCertificateIterator { remaining_data };
+    let mut iter = AttributeCertificateIterator {
+        remaining_data,
+        mode: IterMode::Strict,
+    };
     while iter.next().is_some() {}
     iter.remaining_data.is_empty()
 }
@@ -50,6 +128,13 @@ pub enum AttributeCertificateError {
         /// Size (in bytes) stored in the certificate entry header.
         size: u32,
     },
+
+    /// Non-empty, unparseable bytes remained after the last certificate
+    /// entry. Only returned by iterators created with
+    /// [`AttributeCertificateIterator::new_lenient`]; a strict iterator
+    /// reports the equivalent condition as [`Self::InvalidSize`] at
+    /// construction time instead.
+    TrailingGarbage,
 }
 
 impl Display for AttributeCertificateError {
@@ -64,6 +149,9 @@ impl Display for AttributeCertificateError {
             Self::InvalidCertificateSize { size } => {
                 write!(f, "certificate table contains an entry with an invalid size: {size}")
             }
+            Self::TrailingGarbage => {
+                write!(f, "certificate table has unparseable trailing bytes")
+            }
         }
     }
 }
@@ -103,6 +191,33 @@ impl Display for AttributeCertificateAuthenticodeError {
 #[cfg(feature = "std")]
 impl std::error::Error for AttributeCertificateAuthenticodeError {}
 
+/// Error returned by [`AttributeCertificate::write_to`] and
+/// [`write_attribute_certificate_table`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AttributeCertificateWriteError {
+    /// The output buffer is smaller than [`AttributeCertificate::encoded_len`].
+    BufferTooSmall,
+
+    /// The certificate, including its header, does not fit in a `u32`.
+    TooLarge,
+}
+
+impl Display for AttributeCertificateWriteError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BufferTooSmall => {
+                write!(f, "output buffer is smaller than the encoded certificate")
+            }
+            Self::TooLarge => {
+                write!(f, "certificate does not fit in a WIN_CERTIFICATE's 32-bit dwLength field")
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for AttributeCertificateWriteError {}
+
 /// Raw data for a PE attribute certificate.
 ///
 /// Note that PE attribute certificates are not related to X.509
@@ -120,15 +235,35 @@ pub struct AttributeCertificate<'a> {
 }
 
 impl AttributeCertificate<'_> {
+    /// Get the parsed form of [`Self::revision`].
+    ///
+    /// Returns an error if `revision` is not one of the recognized
+    /// [`AttributeCertificateRevision`] values.
+    pub fn revision_kind(
+        &self,
+    ) -> Result<AttributeCertificateRevision, UnknownAttributeCertificateValue> {
+        self.revision.try_into()
+    }
+
+    /// Get the parsed form of [`Self::certificate_type`].
+    ///
+    /// Returns an error if `certificate_type` is not one of the
+    /// recognized [`AttributeCertificateType`] values.
+    pub fn certificate_type_kind(
+        &self,
+    ) -> Result<AttributeCertificateType, UnknownAttributeCertificateValue> {
+        self.certificate_type.try_into()
+    }
+
     /// Get the certificate data as an authenticode signature.
     pub fn get_authenticode_signature(
         &self,
     ) -> Result<AuthenticodeSignature, AttributeCertificateAuthenticodeError>
     {
-        if self.revision != WIN_CERT_REVISION_2_0 {
+        if self.revision_kind() != Ok(AttributeCertificateRevision::Revision2_0) {
             return Err(AttributeCertificateAuthenticodeError::InvalidCertificateRevision(self.revision));
         }
-        if self.certificate_type != WIN_CERT_TYPE_PKCS_SIGNED_DATA {
+        if self.certificate_type_kind() != Ok(AttributeCertificateType::PkcsSignedData) {
             return Err(
                 AttributeCertificateAuthenticodeError::InvalidCertificateType(
                     self.certificate_type,
@@ -139,12 +274,89 @@ impl AttributeCertificate<'_> {
         AuthenticodeSignature::from_bytes(self.data)
             .map_err(AttributeCertificateAuthenticodeError::InvalidSignature)
     }
+
+    /// Get the number of bytes [`Self::write_to`] writes, i.e. the
+    /// header plus the certificate data, rounded up to an 8-byte
+    /// boundary.
+    pub fn encoded_len(&self) -> usize {
+        // OK to unwrap: a slice is at most `isize::MAX` bytes long, so
+        // adding the header and up to 7 bytes of padding to `data`'s
+        // length cannot overflow `usize`.
+        align_up_to_8(HEADER_SIZE + self.data.len()).unwrap()
+    }
+
+    /// Write this certificate into `out`, framed as a `WIN_CERTIFICATE`
+    /// entry.
+    ///
+    /// `out` must be at least [`Self::encoded_len`] bytes long. Returns
+    /// the number of bytes written, which is always `encoded_len()`.
+    pub fn write_to(&self, out: &mut [u8]) -> Result<usize, AttributeCertificateWriteError> {
+        let unpadded_len = HEADER_SIZE + self.data.len();
+        let cert_size = u32::try_from(unpadded_len)
+            .map_err(|_| AttributeCertificateWriteError::TooLarge)?;
+        let total_len = self.encoded_len();
+
+        let out = out
+            .get_mut(..total_len)
+            .ok_or(AttributeCertificateWriteError::BufferTooSmall)?;
+        out[0..4].copy_from_slice(&cert_size.to_le_bytes());
+        out[4..6].copy_from_slice(&self.revision.to_le_bytes());
+        out[6..8].copy_from_slice(&self.certificate_type.to_le_bytes());
+        out[HEADER_SIZE..unpadded_len].copy_from_slice(self.data);
+        // Zero-pad up to the 8-byte boundary so the written table
+        // round-trips through `AttributeCertificateIterator`.
+        out[unpadded_len..total_len].fill(0);
+
+        Ok(total_len)
+    }
+}
+
+/// Serialize `certificates` into `out` as a contiguous attribute
+/// certificate table, in iteration order.
+///
+/// Each certificate is written via [`AttributeCertificate::write_to`],
+/// so the result is exactly what [`AttributeCertificateIterator`]
+/// expects to read back. Returns the total number of bytes written.
+pub fn write_attribute_certificate_table<'a, I>(
+    certificates: I,
+    out: &mut [u8],
+) -> Result<usize, AttributeCertificateWriteError>
+where
+    I: IntoIterator<Item = &'a AttributeCertificate<'a>>,
+{
+    let mut offset = 0;
+    for certificate in certificates {
+        let out = out
+            .get_mut(offset..)
+            .ok_or(AttributeCertificateWriteError::BufferTooSmall)?;
+        offset += certificate.write_to(out)?;
+    }
+    Ok(offset)
+}
+
+/// Iteration mode for [`AttributeCertificateIterator`], selected by
+/// using [`AttributeCertificateIterator::new`] or
+/// [`AttributeCertificateIterator::new_lenient`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum IterMode {
+    /// Validate the table size up front, and stop iteration as soon as a
+    /// malformed entry is hit.
+    Strict,
+
+    /// Skip the upfront size validation, and on a malformed entry try to
+    /// resynchronize to the next 8-byte-aligned offset instead of
+    /// stopping. A leftover too short to hold an entry header is
+    /// reported as [`AttributeCertificateError::TrailingGarbage`] rather
+    /// than rejected at construction time; a leftover of 8 or more bytes
+    /// is parsed as an entry.
+    Lenient,
 }
 
 /// Iterator over PE attribute certificates.
 #[derive(Debug)]
 pub struct AttributeCertificateIterator<'a> {
     remaining_data: &'a [u8],
+    mode: IterMode,
 }
 
 impl<'a> AttributeCertificateIterator<'a> {
@@ -174,7 +386,45 @@ impl<'a> AttributeCertificateIterator<'a> {
                 if !check_total_size_valid(remaining_data) {
                     return Err(AttributeCertificateError::InvalidSize);
                 }
-                Ok(Some(Self { remaining_data }))
+                Ok(Some(Self {
+                    remaining_data,
+                    mode: IterMode::Strict,
+                }))
+            }
+            Ok(None) => Ok(None),
+            Err(_) => Err(AttributeCertificateError::OutOfBounds),
+        }
+    }
+
+    /// Create a new `AttributeCertificateIterator` for forensic or
+    /// tamper analysis of a possibly-malformed certificate table.
+    ///
+    /// Unlike [`Self::new`], this does not reject the table up front if
+    /// its total size doesn't add up, and it keeps looking for further
+    /// entries after a malformed one instead of stopping. This means a
+    /// single corrupt or truncated entry no longer hides every valid
+    /// certificate that precedes it.
+    ///
+    /// If there is no attribute certificate table, this returns `Ok(None)`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AttributeCertificateError::OutOfBounds`] if the table
+    /// is not within the PE image bounds.
+    pub fn new_lenient(
+        pe: &'a dyn PeTrait,
+    ) -> Result<Option<Self>, AttributeCertificateError> {
+        match pe.certificate_table_range() {
+            Ok(Some(certificate_table_range)) => {
+                let remaining_data = pe
+                    .data()
+                    .get(certificate_table_range)
+                    .ok_or(AttributeCertificateError::OutOfBounds)?;
+
+                Ok(Some(Self {
+                    remaining_data,
+                    mode: IterMode::Lenient,
+                }))
             }
             Ok(None) => Ok(None),
             Err(_) => Err(AttributeCertificateError::OutOfBounds),
@@ -186,8 +436,15 @@ impl<'a> Iterator for AttributeCertificateIterator<'a> {
     type Item = Result<AttributeCertificate<'a>, AttributeCertificateError>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let header_size = 8;
+        let header_size = HEADER_SIZE;
         if self.remaining_data.len() < header_size {
+            // In lenient mode, a non-empty leftover that's too short to
+            // even hold a header is reported once as trailing garbage
+            // rather than silently dropped.
+            if self.mode == IterMode::Lenient && !self.remaining_data.is_empty() {
+                self.remaining_data = &[];
+                return Some(Err(AttributeCertificateError::TrailingGarbage));
+            }
             return None;
         }
 
@@ -210,8 +467,7 @@ impl<'a> Iterator for AttributeCertificateIterator<'a> {
             if let Some(cert_data_size) = cert_size.checked_sub(header_size) {
                 cert_data_size
             } else {
-                // End iteration after returning the error.
-                self.remaining_data = &[];
+                self.skip_malformed_entry(cert_bytes);
                 return Some(Err(cert_size_err));
             };
 
@@ -222,8 +478,7 @@ impl<'a> Iterator for AttributeCertificateIterator<'a> {
         {
             cert_data_end
         } else {
-            // End iteration after returning the error.
-            self.remaining_data = &[];
+            self.skip_malformed_entry(cert_bytes);
             return Some(Err(cert_size_err));
         };
 
@@ -234,14 +489,29 @@ impl<'a> Iterator for AttributeCertificateIterator<'a> {
         {
             cert_data
         } else {
-            // End iteration after returning the error.
-            self.remaining_data = &[];
+            self.skip_malformed_entry(cert_bytes);
             return Some(Err(cert_size_err));
         };
 
         // Advance to next certificate. Data is 8-byte aligned, so round up.
-        let size_rounded_up = align_up_to_8(cert_size)?;
-        self.remaining_data = cert_bytes.get(size_rounded_up..)?;
+        match align_up_to_8(cert_size).and_then(|size_rounded_up| cert_bytes.get(size_rounded_up..))
+        {
+            Some(next) => self.remaining_data = next,
+            None if self.mode == IterMode::Strict => {
+                // Preserve prior behavior: end iteration without
+                // yielding this entry, so that `new`'s upfront
+                // `check_total_size_valid` check is the one that
+                // surfaces this as `InvalidSize`.
+                return None;
+            }
+            None => {
+                // Lenient mode: the padding after this entry is missing
+                // or truncated, but the entry itself parsed fine, so
+                // don't drop it. Keep whatever's left (if anything) so
+                // it can be reported as `TrailingGarbage`.
+                self.remaining_data = cert_bytes.get(cert_data_end..).unwrap_or(&[]);
+            }
+        }
 
         Some(Ok(AttributeCertificate {
             revision,
@@ -251,6 +521,24 @@ impl<'a> Iterator for AttributeCertificateIterator<'a> {
     }
 }
 
+impl<'a> AttributeCertificateIterator<'a> {
+    /// Handle a malformed entry found at the start of `cert_bytes`
+    /// (which is always `self.remaining_data` as it was at the start of
+    /// the `next()` call that found the problem).
+    ///
+    /// In [`IterMode::Strict`] mode, this ends iteration. In
+    /// [`IterMode::Lenient`] mode, this instead resynchronizes to the
+    /// next 8-byte-aligned offset after the malformed entry's header so
+    /// that later, well-formed entries can still be found.
+    fn skip_malformed_entry(&mut self, cert_bytes: &'a [u8]) {
+        self.remaining_data = if self.mode == IterMode::Lenient {
+            cert_bytes.get(HEADER_SIZE..).unwrap_or(&[])
+        } else {
+            &[]
+        };
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -265,4 +553,175 @@ mod tests {
             assert_eq!(align_up_to_8(i).unwrap(), 16);
         }
     }
+
+    #[test]
+    fn test_attribute_certificate_revision_try_from() {
+        assert_eq!(
+            AttributeCertificateRevision::try_from(0x0100),
+            Ok(AttributeCertificateRevision::Revision1_0)
+        );
+        assert_eq!(
+            AttributeCertificateRevision::try_from(0x0200),
+            Ok(AttributeCertificateRevision::Revision2_0)
+        );
+        assert_eq!(
+            AttributeCertificateRevision::try_from(0x0abc),
+            Err(UnknownAttributeCertificateValue(0x0abc))
+        );
+    }
+
+    #[test]
+    fn test_attribute_certificate_type_try_from() {
+        assert_eq!(
+            AttributeCertificateType::try_from(0x0001),
+            Ok(AttributeCertificateType::X509)
+        );
+        assert_eq!(
+            AttributeCertificateType::try_from(0x0002),
+            Ok(AttributeCertificateType::PkcsSignedData)
+        );
+        assert_eq!(
+            AttributeCertificateType::try_from(0x0003),
+            Ok(AttributeCertificateType::Reserved1)
+        );
+        assert_eq!(
+            AttributeCertificateType::try_from(0x0004),
+            Ok(AttributeCertificateType::TsStackSigned)
+        );
+        assert_eq!(
+            AttributeCertificateType::try_from(0x1234),
+            Err(UnknownAttributeCertificateValue(0x1234))
+        );
+    }
+
+    #[test]
+    fn test_write_attribute_certificate_table_round_trip() {
+        let certs = [
+            AttributeCertificate {
+                revision: WIN_CERT_REVISION_2_0,
+                certificate_type: WIN_CERT_TYPE_PKCS_SIGNED_DATA,
+                data: &[1, 2, 3],
+            },
+            AttributeCertificate {
+                revision: WIN_CERT_REVISION_2_0,
+                certificate_type: WIN_CERT_TYPE_PKCS_SIGNED_DATA,
+                data: &[4, 5, 6, 7, 8, 9, 10],
+            },
+        ];
+        let total_len: usize = certs.iter().map(AttributeCertificate::encoded_len).sum();
+        let mut buf = vec![0u8; total_len];
+        let written = write_attribute_certificate_table(certs.iter(), &mut buf).unwrap();
+        assert_eq!(written, total_len);
+        assert!(check_total_size_valid(&buf));
+
+        let iter = AttributeCertificateIterator {
+            remaining_data: &buf,
+            mode: IterMode::Strict,
+        };
+        let parsed: Vec<_> = iter.map(|cert| cert.unwrap()).collect();
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[0].data, &[1, 2, 3]);
+        assert_eq!(parsed[1].data, &[4, 5, 6, 7, 8, 9, 10]);
+    }
+
+    #[test]
+    fn test_write_to_buffer_too_small() {
+        let cert = AttributeCertificate {
+            revision: WIN_CERT_REVISION_2_0,
+            certificate_type: WIN_CERT_TYPE_PKCS_SIGNED_DATA,
+            data: &[1, 2, 3],
+        };
+        let mut buf = vec![0u8; cert.encoded_len() - 1];
+        assert_eq!(
+            cert.write_to(&mut buf),
+            Err(AttributeCertificateWriteError::BufferTooSmall)
+        );
+    }
+
+    #[test]
+    fn test_lenient_resync_after_malformed_entry() {
+        let cert1 = AttributeCertificate {
+            revision: WIN_CERT_REVISION_2_0,
+            certificate_type: WIN_CERT_TYPE_PKCS_SIGNED_DATA,
+            data: &[1, 2, 3],
+        };
+        let cert2 = AttributeCertificate {
+            revision: WIN_CERT_REVISION_2_0,
+            certificate_type: WIN_CERT_TYPE_PKCS_SIGNED_DATA,
+            data: &[9, 9],
+        };
+
+        let mut buf = vec![0u8; cert1.encoded_len()];
+        cert1.write_to(&mut buf).unwrap();
+
+        // A malformed entry whose declared size is smaller than the
+        // header itself.
+        buf.extend_from_slice(&4u32.to_le_bytes());
+        buf.extend_from_slice(&WIN_CERT_REVISION_2_0.to_le_bytes());
+        buf.extend_from_slice(&WIN_CERT_TYPE_PKCS_SIGNED_DATA.to_le_bytes());
+
+        let cert2_start = buf.len();
+        buf.resize(cert2_start + cert2.encoded_len(), 0);
+        cert2.write_to(&mut buf[cert2_start..]).unwrap();
+
+        let iter = AttributeCertificateIterator {
+            remaining_data: &buf,
+            mode: IterMode::Lenient,
+        };
+        let results: Vec<_> = iter.collect();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].as_ref().unwrap().data, &[1, 2, 3]);
+        assert_eq!(
+            results[1].as_ref().err(),
+            Some(&AttributeCertificateError::InvalidCertificateSize { size: 4 })
+        );
+        assert_eq!(results[2].as_ref().unwrap().data, &[9, 9]);
+    }
+
+    #[test]
+    fn test_lenient_trailing_garbage() {
+        let cert = AttributeCertificate {
+            revision: WIN_CERT_REVISION_2_0,
+            certificate_type: WIN_CERT_TYPE_PKCS_SIGNED_DATA,
+            data: &[1, 2, 3],
+        };
+        let mut buf = vec![0u8; cert.encoded_len()];
+        cert.write_to(&mut buf).unwrap();
+        // Too short to be a valid header.
+        buf.extend_from_slice(&[0xaa, 0xbb, 0xcc]);
+
+        let iter = AttributeCertificateIterator {
+            remaining_data: &buf,
+            mode: IterMode::Lenient,
+        };
+        let results: Vec<_> = iter.collect();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].as_ref().unwrap().data, &[1, 2, 3]);
+        assert_eq!(
+            results[1].as_ref().err(),
+            Some(&AttributeCertificateError::TrailingGarbage)
+        );
+    }
+
+    #[test]
+    fn test_lenient_keeps_final_entry_missing_padding() {
+        // The last entry's data isn't a multiple of 8 bytes, and the
+        // buffer ends right after it with no padding bytes present.
+        let data = [1, 2, 3];
+        let cert_size = u32::try_from(HEADER_SIZE + data.len()).unwrap();
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&cert_size.to_le_bytes());
+        buf.extend_from_slice(&WIN_CERT_REVISION_2_0.to_le_bytes());
+        buf.extend_from_slice(&WIN_CERT_TYPE_PKCS_SIGNED_DATA.to_le_bytes());
+        buf.extend_from_slice(&data);
+        assert_ne!(buf.len() % 8, 0);
+
+        let iter = AttributeCertificateIterator {
+            remaining_data: &buf,
+            mode: IterMode::Lenient,
+        };
+        let results: Vec<_> = iter.collect();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].as_ref().unwrap().data, &data);
+    }
 }